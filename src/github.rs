@@ -3,15 +3,17 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use {
-    anyhow::{anyhow, Result},
-    clap::ArgMatches,
+    anyhow::{anyhow, Context, Result},
+    clap::{App, Arg, ArgMatches, SubCommand},
     octocrab::OctocrabBuilder,
     once_cell::sync::Lazy,
-    serde::Deserialize,
+    rand::Rng,
+    serde::{Deserialize, Serialize},
+    sha2::{Digest, Sha256},
     std::{
         collections::{BTreeMap, BTreeSet},
-        io::Read,
-        path::PathBuf,
+        io::{Read, Write},
+        path::{Path, PathBuf},
     },
     zip::ZipArchive,
 };
@@ -43,6 +45,143 @@ static SUFFIXES_BY_TRIPLE: Lazy<BTreeMap<&'static str, Vec<&'static str>>> = Laz
     h
 });
 
+// Per-triple suffixes plus optional per-suffix extension overrides, loaded from `--targets`.
+#[derive(Clone, Debug, Deserialize)]
+struct TargetConfig {
+    suffixes: Vec<String>,
+    #[serde(default)]
+    extensions: BTreeMap<String, String>,
+}
+
+// Shape of a `--targets targets.toml`/`.json` file.
+#[derive(Clone, Debug, Deserialize)]
+struct TargetsConfig {
+    targets: BTreeMap<String, TargetConfig>,
+}
+
+fn suffix_extension(target: &TargetConfig, suffix: &str) -> String {
+    target.extensions.get(suffix).cloned().unwrap_or_else(|| {
+        if suffix.contains("install_only") {
+            "tar.gz".to_string()
+        } else {
+            "tar.zst".to_string()
+        }
+    })
+}
+
+// Loads the triple/suffix matrix from `--targets` (falling back to SUFFIXES_BY_TRIPLE), then
+// applies `--only-triple`/`--only-suffix`.
+fn load_targets(args: &ArgMatches) -> Result<BTreeMap<String, TargetConfig>> {
+    let targets = match args.value_of("targets") {
+        Some(path) => {
+            let contents = std::fs::read_to_string(path)
+                .with_context(|| format!("reading targets config {}", path))?;
+
+            let config: TargetsConfig = if path.ends_with(".json") {
+                serde_json::from_str(&contents)
+                    .with_context(|| format!("parsing targets config {}", path))?
+            } else {
+                toml::from_str(&contents)
+                    .with_context(|| format!("parsing targets config {}", path))?
+            };
+
+            config.targets
+        }
+        None => SUFFIXES_BY_TRIPLE
+            .iter()
+            .map(|(triple, suffixes)| {
+                (
+                    triple.to_string(),
+                    TargetConfig {
+                        suffixes: suffixes.iter().map(|suffix| suffix.to_string()).collect(),
+                        extensions: BTreeMap::new(),
+                    },
+                )
+            })
+            .collect(),
+    };
+
+    let only_triples = args
+        .values_of("only_triple")
+        .map(|values| values.collect::<BTreeSet<_>>());
+    let only_suffixes = args
+        .values_of("only_suffix")
+        .map(|values| values.collect::<BTreeSet<_>>());
+
+    Ok(targets
+        .into_iter()
+        .filter(|(triple, _)| {
+            only_triples
+                .as_ref()
+                .map_or(true, |wanted| wanted.contains(triple.as_str()))
+        })
+        .map(|(triple, mut target)| {
+            if let Some(wanted) = &only_suffixes {
+                target
+                    .suffixes
+                    .retain(|suffix| wanted.contains(suffix.as_str()));
+            }
+            (triple, target)
+        })
+        .filter(|(_, target)| !target.suffixes.is_empty())
+        .collect())
+}
+
+// Lower rank wins; used to pick the `preferred` suffix for a triple.
+fn suffix_rank(suffix: &str) -> u8 {
+    match suffix {
+        "pgo+lto" => 0,
+        "pgo" | "shared-pgo" => 1,
+        "lto" => 2,
+        "noopt" | "static-noopt" => 3,
+        "debug" => 4,
+        "install_only" | "shared-install_only" => 5,
+        _ => 6,
+    }
+}
+
+// cpython-{version}-{triple}-{suffix}-{datetime}.{ext}, decomposed.
+#[derive(Clone, Debug)]
+struct WantedArtifact {
+    filename: String,
+    python_version: String,
+    triple: String,
+    suffix: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct ManifestEntry {
+    size: u64,
+    sha256: String,
+    python_version: String,
+    triple: String,
+    suffix: String,
+    datetime: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct AssetIndexEntry {
+    filename: String,
+    download_url: String,
+    size: u64,
+    sha256: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct TripleIndex {
+    suffixes: BTreeMap<String, AssetIndexEntry>,
+    preferred: String,
+}
+
+// Published as `release-<datetime>.json` and the rolling `latest.json`.
+#[derive(Clone, Debug, Serialize)]
+struct ReleaseIndex {
+    version: String,
+    datetime: String,
+    tag: String,
+    python_versions: BTreeMap<String, BTreeMap<String, TripleIndex>>,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 struct Artifact {
     archive_download_url: String,
@@ -63,12 +202,63 @@ struct Artifacts {
     total_count: u64,
 }
 
+// Registers every flag `command_fetch_release_distributions` reads from its `ArgMatches`.
+pub fn fetch_release_distributions_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("fetch-release-distributions")
+        .about("Fetch CPython release distributions from GitHub Actions artifacts")
+        .arg(
+            Arg::with_name("dest")
+                .long("dest")
+                .takes_value(true)
+                .required(true)
+                .help("Directory to write fetched distributions to"),
+        )
+        .arg(
+            Arg::with_name("organization")
+                .long("organization")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(Arg::with_name("repo").long("repo").takes_value(true).required(true))
+        .arg(
+            Arg::with_name("token")
+                .long("token")
+                .takes_value(true)
+                .required(true)
+                .help("GitHub API token"),
+        )
+        .arg(
+            Arg::with_name("commit")
+                .long("commit")
+                .takes_value(true)
+                .required(true)
+                .help("The commit to fetch successful workflow run artifacts for"),
+        )
+        .arg(
+            Arg::with_name("concurrency")
+                .long("concurrency")
+                .takes_value(true)
+                .help("Maximum number of artifacts to download at once (default 4)"),
+        )
+        .args(&targets_args())
+}
+
 pub async fn command_fetch_release_distributions(args: &ArgMatches<'_>) -> Result<()> {
     let dest_dir = PathBuf::from(args.value_of("dest").expect("dest directory should be set"));
     let org = args
         .value_of("organization")
         .expect("organization should be set");
     let repo = args.value_of("repo").expect("repo should be set");
+    let concurrency = args
+        .value_of("concurrency")
+        .map(str::parse)
+        .transpose()
+        .context("parsing --concurrency")?
+        .unwrap_or(DEFAULT_FETCH_CONCURRENCY);
+    if concurrency < 1 {
+        return Err(anyhow!("--concurrency must be at least 1"));
+    }
+    let targets = load_targets(args)?;
 
     let client = OctocrabBuilder::new()
         .personal_token(
@@ -106,7 +296,7 @@ pub async fn command_fetch_release_distributions(args: &ArgMatches<'_>) -> Resul
         );
     }
 
-    let mut fs = vec![];
+    let mut artifacts = vec![];
 
     for run in runs {
         let res = client
@@ -117,9 +307,9 @@ pub async fn command_fetch_release_distributions(args: &ArgMatches<'_>) -> Resul
             return Err(anyhow!("non-HTTP 200 fetching artifacts"));
         }
 
-        let artifacts: Artifacts = res.json().await?;
+        let run_artifacts: Artifacts = res.json().await?;
 
-        for artifact in artifacts.artifacts {
+        for artifact in run_artifacts.artifacts {
             if matches!(
                 artifact.name.as_str(),
                 "pythonbuild" | "sccache" | "toolchain"
@@ -127,34 +317,55 @@ pub async fn command_fetch_release_distributions(args: &ArgMatches<'_>) -> Resul
                 continue;
             }
 
-            println!("downloading {}", artifact.name);
-            let res = client
-                .execute(
-                    client.request_builder(artifact.archive_download_url, reqwest::Method::GET),
-                )
-                .await?;
-
-            fs.push(res.bytes());
+            artifacts.push(artifact);
         }
     }
 
-    for res in futures::future::join_all(fs).await {
-        let data = res?;
+    let semaphore = std::sync::Arc::new(tokio::sync::Semaphore::new(concurrency));
+
+    let downloads = artifacts.into_iter().map(|artifact| {
+        let client = client.clone();
+        let semaphore = semaphore.clone();
+
+        async move {
+            let _permit = semaphore
+                .acquire_owned()
+                .await
+                .expect("semaphore should not be closed");
+
+            let result = download_artifact_with_retry(&client, &artifact).await;
+            (artifact.name, result)
+        }
+    });
+
+    let mut failed = vec![];
+    let mut fetched = vec![];
+
+    for (name, result) in futures::future::join_all(downloads).await {
+        match result {
+            Ok(data) => fetched.push(data),
+            Err(err) => {
+                eprintln!("giving up on {}: {:#}", name, err);
+                failed.push(name);
+            }
+        }
+    }
 
+    for data in fetched {
         let mut za = ZipArchive::new(std::io::Cursor::new(data))?;
         for i in 0..za.len() {
             let mut zf = za.by_index(i)?;
 
             let name = zf.name().to_string();
 
-            if let Some(suffixes) = SUFFIXES_BY_TRIPLE.iter().find_map(|(triple, suffixes)| {
+            if let Some(target) = targets.iter().find_map(|(triple, target)| {
                 if name.contains(triple) {
-                    Some(suffixes)
+                    Some(target)
                 } else {
                     None
                 }
             }) {
-                if suffixes.iter().any(|suffix| name.contains(suffix)) {
+                if target.suffixes.iter().any(|suffix| name.contains(suffix)) {
                     let dest_path = dest_dir.join(&name);
                     let mut buf = vec![];
                     zf.read_to_end(&mut buf)?;
@@ -170,9 +381,169 @@ pub async fn command_fetch_release_distributions(args: &ArgMatches<'_>) -> Resul
         }
     }
 
+    if !failed.is_empty() {
+        return Err(anyhow!(
+            "failed to download artifacts after retrying: {}",
+            failed.join(", ")
+        ));
+    }
+
     Ok(())
 }
 
+const DEFAULT_FETCH_CONCURRENCY: usize = 4;
+const MAX_DOWNLOAD_ATTEMPTS: u32 = 5;
+
+// Retries any failed attempt (HTTP error status, network error or size mismatch), up to
+// MAX_DOWNLOAD_ATTEMPTS in all, with exponential backoff plus jitter; errors are not classified
+// as transient or permanent.
+async fn download_artifact_with_retry(
+    client: &octocrab::Octocrab,
+    artifact: &Artifact,
+) -> Result<bytes::Bytes> {
+    let mut attempt = 0;
+
+    loop {
+        attempt += 1;
+
+        match download_artifact_once(client, artifact).await {
+            Ok(data) => return Ok(data),
+            Err(err) if attempt < MAX_DOWNLOAD_ATTEMPTS => {
+                let backoff = std::time::Duration::from_millis(250 * 2u64.pow(attempt - 1));
+                let jitter =
+                    std::time::Duration::from_millis(rand::thread_rng().gen_range(0..250));
+
+                println!(
+                    "download of {} failed (attempt {}/{}), retrying in {:?}: {:#}",
+                    artifact.name, attempt, MAX_DOWNLOAD_ATTEMPTS, backoff + jitter, err
+                );
+
+                tokio::time::sleep(backoff + jitter).await;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+async fn download_artifact_once(
+    client: &octocrab::Octocrab,
+    artifact: &Artifact,
+) -> Result<bytes::Bytes> {
+    println!("downloading {}", artifact.name);
+
+    let res = client
+        .execute(client.request_builder(
+            artifact.archive_download_url.clone(),
+            reqwest::Method::GET,
+        ))
+        .await?;
+
+    if !res.status().is_success() {
+        return Err(anyhow!(
+            "HTTP {} downloading {}",
+            res.status(),
+            artifact.name
+        ));
+    }
+
+    let data = res.bytes().await?;
+
+    if data.len() as u64 != artifact.size_in_bytes {
+        return Err(anyhow!(
+            "downloaded {} bytes for {} but expected {}",
+            data.len(),
+            artifact.name,
+            artifact.size_in_bytes
+        ));
+    }
+
+    Ok(data)
+}
+
+// Registers every flag `command_upload_release_distributions` reads from its `ArgMatches`.
+pub fn upload_release_distributions_subcommand() -> App<'static, 'static> {
+    SubCommand::with_name("upload-release-distributions")
+        .about("Upload CPython release distributions to a GitHub release")
+        .arg(
+            Arg::with_name("dist")
+                .long("dist")
+                .takes_value(true)
+                .required(true)
+                .help("Directory containing the built distributions"),
+        )
+        .arg(
+            Arg::with_name("datetime")
+                .long("datetime")
+                .takes_value(true)
+                .required(true)
+                .help("The build datetime embedded in distribution filenames"),
+        )
+        .arg(
+            Arg::with_name("tag")
+                .long("tag")
+                .takes_value(true)
+                .required(true)
+                .help("The existing GitHub release tag to upload to"),
+        )
+        .arg(
+            Arg::with_name("ignore_missing")
+                .long("ignore-missing")
+                .help("Don't fail if some wanted distributions are missing from --dist"),
+        )
+        .arg(
+            Arg::with_name("force")
+                .long("force")
+                .help("Always replace existing release assets, even if unchanged"),
+        )
+        .arg(
+            Arg::with_name("token")
+                .long("token")
+                .takes_value(true)
+                .required(true)
+                .help("GitHub API token"),
+        )
+        .arg(
+            Arg::with_name("organization")
+                .long("organization")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(Arg::with_name("repo").long("repo").takes_value(true).required(true))
+        .arg(
+            Arg::with_name("signing_key")
+                .long("signing-key")
+                .takes_value(true)
+                .help("Path to an OpenPGP secret key used to sign SHA256SUMS"),
+        )
+        .arg(
+            Arg::with_name("signing_key_passphrase")
+                .long("signing-key-passphrase")
+                .takes_value(true)
+                .help("Passphrase for --signing-key, if its secret material is encrypted"),
+        )
+        .args(&targets_args())
+}
+
+// Flags read by `load_targets`, shared by both subcommands it's used from.
+fn targets_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("targets")
+            .long("targets")
+            .takes_value(true)
+            .help("Path to a TOML/JSON file overriding the built-in triple/suffix matrix"),
+        Arg::with_name("only_triple")
+            .long("only-triple")
+            .takes_value(true)
+            .multiple(true)
+            .help("Restrict to this triple (repeatable)"),
+        Arg::with_name("only_suffix")
+            .long("only-suffix")
+            .takes_value(true)
+            .multiple(true)
+            .help("Restrict to this suffix (repeatable)"),
+    ]
+}
+
 pub async fn command_upload_release_distributions(args: &ArgMatches<'_>) -> Result<()> {
     let dist_dir = PathBuf::from(args.value_of("dist").expect("dist should be specified"));
     let datetime = args
@@ -180,6 +551,7 @@ pub async fn command_upload_release_distributions(args: &ArgMatches<'_>) -> Resu
         .expect("datetime should be specified");
     let tag = args.value_of("tag").expect("tag should be specified");
     let ignore_missing = args.is_present("ignore_missing");
+    let force = args.is_present("force");
     let token = args
         .value_of("token")
         .expect("token should be specified")
@@ -213,24 +585,32 @@ pub async fn command_upload_release_distributions(args: &ArgMatches<'_>) -> Resu
         python_versions.insert(parts[1]);
     }
 
-    let mut wanted_filenames = BTreeSet::new();
+    let targets = load_targets(args)?;
+
+    let mut wanted_artifacts = vec![];
     for version in python_versions {
-        for (triple, suffixes) in SUFFIXES_BY_TRIPLE.iter() {
-            for suffix in suffixes {
-                let extension = if suffix.contains("install_only") {
-                    "tar.gz"
-                } else {
-                    "tar.zst"
-                };
+        for (triple, target) in &targets {
+            for suffix in &target.suffixes {
+                let extension = suffix_extension(target, suffix);
 
-                wanted_filenames.insert(format!(
-                    "cpython-{}-{}-{}-{}.{}",
-                    version, triple, suffix, datetime, extension
-                ));
+                wanted_artifacts.push(WantedArtifact {
+                    filename: format!(
+                        "cpython-{}-{}-{}-{}.{}",
+                        version, triple, suffix, datetime, extension
+                    ),
+                    python_version: version.to_string(),
+                    triple: triple.to_string(),
+                    suffix: suffix.to_string(),
+                });
             }
         }
     }
 
+    let wanted_filenames = wanted_artifacts
+        .iter()
+        .map(|artifact| artifact.filename.clone())
+        .collect::<BTreeSet<_>>();
+
     let missing = wanted_filenames.difference(&filenames).collect::<Vec<_>>();
     for f in &missing {
         println!("missing release artifact: {}", f);
@@ -252,35 +632,425 @@ pub async fn command_upload_release_distributions(args: &ArgMatches<'_>) -> Resu
         ));
     };
 
-    for filename in wanted_filenames.intersection(&filenames) {
+    let old_manifest = fetch_existing_manifest(&client, &release).await?;
+
+    // Seed from the manifest already on the release so that a filtered
+    // `--only-triple`/`--only-suffix` run doesn't drop the other triples'
+    // entries from `manifest.json`/`SHA256SUMS` — it only replaces the
+    // entries it actually re-uploads below.
+    let mut manifest = old_manifest.clone();
+
+    for artifact in wanted_artifacts
+        .iter()
+        .filter(|artifact| filenames.contains(&artifact.filename))
+    {
+        let filename = &artifact.filename;
         let path = dist_dir.join(filename);
         let file_data = std::fs::read(&path)?;
 
-        let mut url = release.upload_url.clone();
-        let path = url.path().to_string();
+        let sha256 = hex::encode(Sha256::digest(&file_data));
+        let existing_sha256 = old_manifest.get(filename).map(|entry| entry.sha256.clone());
+        manifest.insert(
+            filename.clone(),
+            ManifestEntry {
+                size: file_data.len() as u64,
+                sha256,
+                python_version: artifact.python_version.clone(),
+                triple: artifact.triple.clone(),
+                suffix: artifact.suffix.clone(),
+                datetime: datetime.to_string(),
+            },
+        );
 
-        if let Some(path) = path.strip_suffix("%7B") {
-            url.set_path(path);
-        }
+        upsert_release_asset(
+            &client,
+            &release,
+            filename,
+            "application/x-tar",
+            file_data,
+            existing_sha256.as_deref(),
+            force,
+        )
+        .await?;
+    }
+
+    // `manifest` is a `BTreeMap`, so this is already sorted by filename.
+    let sha256sums_lines = manifest
+        .iter()
+        .map(|(filename, entry)| format!("{}  {}", entry.sha256, filename))
+        .collect::<Vec<_>>();
+    let sha256sums = sha256sums_lines.join("\n") + "\n";
+
+    // SHA256SUMS, manifest.json and SHA256SUMS.asc are fully regenerated from `manifest` every
+    // run and are cheap to rebuild, so always replace them outright rather than relying on the
+    // size-only fallback `upsert_release_asset` uses without a known sha256 — a corrected
+    // re-upload of a same-size file would otherwise leave these derived assets stale.
+    upsert_release_asset(
+        &client,
+        &release,
+        "SHA256SUMS",
+        "text/plain",
+        sha256sums.clone().into_bytes(),
+        None,
+        true,
+    )
+    .await?;
+
+    let manifest_json = serde_json::to_vec_pretty(&manifest)?;
+    upsert_release_asset(
+        &client,
+        &release,
+        "manifest.json",
+        "application/json",
+        manifest_json,
+        None,
+        true,
+    )
+    .await?;
+
+    if let Some(signing_key) = args.value_of("signing_key") {
+        let passphrase = args.value_of("signing_key_passphrase");
+        let signature = sign_detached(Path::new(signing_key), passphrase, sha256sums.as_bytes())
+            .context("signing SHA256SUMS")?;
+
+        upsert_release_asset(
+            &client,
+            &release,
+            "SHA256SUMS.asc",
+            "text/plain",
+            signature,
+            None,
+            true,
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+async fn upload_release_asset(
+    client: &octocrab::Octocrab,
+    release: &octocrab::models::repos::Release,
+    name: &str,
+    content_type: &str,
+    data: Vec<u8>,
+) -> Result<()> {
+    let mut url = release.upload_url.clone();
+    let path = url.path().to_string();
 
-        url.query_pairs_mut()
-            .clear()
-            .append_pair("name", filename.as_str());
+    if let Some(path) = path.strip_suffix("%7B") {
+        url.set_path(path);
+    }
+
+    url.query_pairs_mut().clear().append_pair("name", name);
 
-        println!("uploading {} to {}", filename, url);
+    println!("uploading {} to {}", name, url);
 
-        let request = client
-            .request_builder(url, reqwest::Method::POST)
-            .header("Content-Length", file_data.len())
-            .header("Content-Type", "application/x-tar")
-            .body(file_data);
+    let request = client
+        .request_builder(url, reqwest::Method::POST)
+        .header("Content-Length", data.len())
+        .header("Content-Type", content_type)
+        .body(data);
 
+    let response = client.execute(request).await?;
+
+    if !response.status().is_success() {
+        return Err(anyhow!("HTTP {}", response.status()));
+    }
+
+    Ok(())
+}
+
+// Replaces an existing asset of the same name if its content differs (by sha256 if known, else
+// size), or unconditionally when `force` is set. Skips the upload if unchanged.
+async fn upsert_release_asset(
+    client: &octocrab::Octocrab,
+    release: &octocrab::models::repos::Release,
+    name: &str,
+    content_type: &str,
+    data: Vec<u8>,
+    existing_sha256: Option<&str>,
+    force: bool,
+) -> Result<()> {
+    if let Some(existing) = release.assets.iter().find(|asset| asset.name == name) {
+        let unchanged = match existing_sha256 {
+            Some(existing_sha256) => existing_sha256 == hex::encode(Sha256::digest(&data)),
+            None => existing.size as u64 == data.len() as u64,
+        };
+
+        if unchanged && !force {
+            println!("{} is already up to date, skipping", name);
+            return Ok(());
+        }
+
+        println!("replacing stale asset {}", name);
+        let request = client.request_builder(existing.url.clone(), reqwest::Method::DELETE);
         let response = client.execute(request).await?;
 
         if !response.status().is_success() {
-            return Err(anyhow!("HTTP {}", response.status()));
+            return Err(anyhow!(
+                "HTTP {} deleting stale asset {}",
+                response.status(),
+                name
+            ));
+        }
+    }
+
+    upload_release_asset(client, release, name, content_type, data).await
+}
+
+// Returns an empty map if the release has no prior manifest.json.
+async fn fetch_existing_manifest(
+    client: &octocrab::Octocrab,
+    release: &octocrab::models::repos::Release,
+) -> Result<BTreeMap<String, ManifestEntry>> {
+    let asset = match release.assets.iter().find(|asset| asset.name == "manifest.json") {
+        Some(asset) => asset,
+        None => return Ok(BTreeMap::new()),
+    };
+
+    let request = client
+        .request_builder(asset.url.clone(), reqwest::Method::GET)
+        .header(reqwest::header::ACCEPT, "application/octet-stream");
+    let res = client.execute(request).await?;
+
+    if !res.status().is_success() {
+        return Ok(BTreeMap::new());
+    }
+
+    Ok(res.json().await.unwrap_or_default())
+}
+
+// Produces an ASCII-armored detached OpenPGP signature over `message`.
+fn sign_detached(key_path: &Path, passphrase: Option<&str>, message: &[u8]) -> Result<Vec<u8>> {
+    use sequoia_openpgp::{
+        cert::Cert,
+        parse::Parse,
+        policy::StandardPolicy,
+        serialize::stream::{Armorer, Message, Signer},
+    };
+
+    let cert = Cert::from_file(key_path).context("reading signing key")?;
+    let policy = StandardPolicy::new();
+
+    let key = cert
+        .keys()
+        .secret()
+        .with_policy(&policy, None)
+        .alive()
+        .revoked(false)
+        .for_signing()
+        .next()
+        .ok_or_else(|| anyhow!("signing key has no usable signing subkey"))?
+        .key()
+        .clone();
+
+    // Only unprotected CI signing keys are the common case; don't force a
+    // decrypt on a key that isn't actually encrypted.
+    let keypair = if key.secret().is_encrypted() {
+        key.decrypt_secret(&passphrase.unwrap_or_default().into())
+            .context("decrypting signing key")?
+            .into_keypair()?
+    } else {
+        key.into_keypair()?
+    };
+
+    let mut sink = vec![];
+    {
+        let writer = Message::new(&mut sink);
+        let writer = Armorer::new(writer).build()?;
+        let mut signer = Signer::new(writer, keypair).detached().build()?;
+        signer.write_all(message)?;
+        signer.finalize()?;
+    }
+
+    Ok(sink)
+}
+
+// Requires command_upload_release_distributions to have already run for this release, since the
+// index is derived from its manifest.json asset.
+pub async fn command_generate_release_index(args: &ArgMatches<'_>) -> Result<()> {
+    let datetime = args
+        .value_of("datetime")
+        .expect("datetime should be specified");
+    let tag = args.value_of("tag").expect("tag should be specified");
+    let token = args
+        .value_of("token")
+        .expect("token should be specified")
+        .to_string();
+    let organization = args
+        .value_of("organization")
+        .expect("organization should be specified");
+    let repo = args.value_of("repo").expect("repo should be specified");
+
+    let client = OctocrabBuilder::new().personal_token(token).build()?;
+    let releases = client.repos(organization, repo).releases();
+
+    let release = if let Ok(release) = releases.get_by_tag(tag).await {
+        release
+    } else {
+        return Err(anyhow!(
+            "release {} does not exist; create it via GitHub web UI",
+            tag
+        ));
+    };
+
+    let manifest_asset = release
+        .assets
+        .iter()
+        .find(|asset| asset.name == "manifest.json")
+        .ok_or_else(|| {
+            anyhow!(
+                "release {} has no manifest.json; upload distributions first",
+                tag
+            )
+        })?;
+
+    let request = client
+        .request_builder(manifest_asset.url.clone(), reqwest::Method::GET)
+        .header(reqwest::header::ACCEPT, "application/octet-stream");
+    let res = client.execute(request).await?;
+
+    if !res.status().is_success() {
+        return Err(anyhow!("HTTP {} fetching manifest.json", res.status()));
+    }
+
+    let manifest: BTreeMap<String, ManifestEntry> = res.json().await?;
+
+    let mut python_versions: BTreeMap<String, BTreeMap<String, TripleIndex>> = BTreeMap::new();
+
+    for (filename, entry) in &manifest {
+        let asset = release
+            .assets
+            .iter()
+            .find(|asset| &asset.name == filename)
+            .ok_or_else(|| {
+                anyhow!(
+                    "manifest.json references {} but the release has no matching asset",
+                    filename
+                )
+            })?;
+
+        let index_entry = AssetIndexEntry {
+            filename: filename.clone(),
+            download_url: asset.browser_download_url.to_string(),
+            size: entry.size,
+            sha256: entry.sha256.clone(),
+        };
+
+        python_versions
+            .entry(entry.python_version.clone())
+            .or_insert_with(BTreeMap::new)
+            .entry(entry.triple.clone())
+            .or_insert_with(|| TripleIndex {
+                suffixes: BTreeMap::new(),
+                preferred: String::new(),
+            })
+            .suffixes
+            .insert(entry.suffix.clone(), index_entry);
+    }
+
+    for triples in python_versions.values_mut() {
+        for triple_index in triples.values_mut() {
+            triple_index.preferred = triple_index
+                .suffixes
+                .keys()
+                .min_by_key(|suffix| suffix_rank(suffix))
+                .expect("a triple index always has at least one suffix")
+                .clone();
         }
     }
 
+    let index = ReleaseIndex {
+        version: tag.to_string(),
+        datetime: datetime.to_string(),
+        tag: tag.to_string(),
+        python_versions,
+    };
+
+    let index_json = serde_json::to_vec_pretty(&index)?;
+
+    upsert_release_asset(
+        &client,
+        &release,
+        &format!("release-{}.json", datetime),
+        "application/json",
+        index_json.clone(),
+        None,
+        false,
+    )
+    .await?;
+
+    // `latest.json` always points at this release's index, regardless of
+    // whether an older one happens to have the same size.
+    upsert_release_asset(
+        &client,
+        &release,
+        "latest.json",
+        "application/json",
+        index_json,
+        None,
+        true,
+    )
+    .await?;
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches<'a>(args: &[&'a str]) -> ArgMatches<'a> {
+        App::new("test")
+            .args(&targets_args())
+            .get_matches_from(std::iter::once("test").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn only_triple_drops_other_triples() {
+        let args = matches(&["--only-triple", "x86_64-unknown-linux-gnu"]);
+        let targets = load_targets(&args).unwrap();
+
+        assert!(targets.contains_key("x86_64-unknown-linux-gnu"));
+        assert!(!targets.contains_key("aarch64-apple-darwin"));
+    }
+
+    #[test]
+    fn only_suffix_narrows_suffixes_and_drops_now_empty_triples() {
+        // Windows only has shared-pgo/static-noopt/shared-install_only, so
+        // filtering to "debug" should drop it entirely while triples that do
+        // have "debug" (e.g. macOS) keep just that one suffix.
+        let args = matches(&["--only-suffix", "debug"]);
+        let targets = load_targets(&args).unwrap();
+
+        assert!(!targets.contains_key("x86_64-pc-windows-msvc"));
+        assert_eq!(
+            targets["x86_64-apple-darwin"].suffixes,
+            vec!["debug".to_string()]
+        );
+    }
+
+    #[test]
+    fn suffix_extension_falls_back_to_built_in_rule() {
+        let target = TargetConfig {
+            suffixes: vec!["pgo".to_string(), "install_only".to_string()],
+            extensions: BTreeMap::new(),
+        };
+
+        assert_eq!(suffix_extension(&target, "pgo"), "tar.zst");
+        assert_eq!(suffix_extension(&target, "install_only"), "tar.gz");
+    }
+
+    #[test]
+    fn suffix_extension_honors_override() {
+        let mut extensions = BTreeMap::new();
+        extensions.insert("install_only".to_string(), "zip".to_string());
+        let target = TargetConfig {
+            suffixes: vec!["install_only".to_string()],
+            extensions,
+        };
+
+        assert_eq!(suffix_extension(&target, "install_only"), "zip");
+    }
+}